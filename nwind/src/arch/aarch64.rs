@@ -0,0 +1,323 @@
+use gimli::LittleEndian;
+use crate::arch::{Architecture, Registers, UnwindStatus};
+use crate::address_space::{MemoryReader, lookup_binary};
+use crate::types::{Endianness, Bitness};
+use crate::dwarf::{UnwindInfoCache, unwind, unwind_from_cache};
+use crate::dwarf::Error as DwarfError;
+
+// Source: DWARF for the ARM 64-Bit Architecture (AArch64)
+//         https://github.com/ARM-software/abi-aa/blob/main/aadwarf64/aadwarf64.rst
+pub mod dwarf_regs {
+    pub const X0: u16 = 0;
+    pub const X1: u16 = 1;
+    pub const X2: u16 = 2;
+    pub const X3: u16 = 3;
+    pub const X4: u16 = 4;
+    pub const X5: u16 = 5;
+    pub const X6: u16 = 6;
+    pub const X7: u16 = 7;
+    pub const X8: u16 = 8;
+    pub const X9: u16 = 9;
+    pub const X10: u16 = 10;
+    pub const X11: u16 = 11;
+    pub const X12: u16 = 12;
+    pub const X13: u16 = 13;
+    pub const X14: u16 = 14;
+    pub const X15: u16 = 15;
+    pub const X16: u16 = 16;
+    pub const X17: u16 = 17;
+    pub const X18: u16 = 18;
+    pub const X19: u16 = 19;
+    pub const X20: u16 = 20;
+    pub const X21: u16 = 21;
+    pub const X22: u16 = 22;
+    pub const X23: u16 = 23;
+    pub const X24: u16 = 24;
+    pub const X25: u16 = 25;
+    pub const X26: u16 = 26;
+    pub const X27: u16 = 27;
+    pub const X28: u16 = 28;
+    pub const X29: u16 = 29; // Frame pointer (FP)
+    pub const X30: u16 = 30; // Link register (LR)
+    pub const SP: u16 = 31;
+    pub const PC: u16 = 32;
+}
+
+static REGS: &'static [u16] = &[
+    dwarf_regs::X0,
+    dwarf_regs::X1,
+    dwarf_regs::X2,
+    dwarf_regs::X3,
+    dwarf_regs::X4,
+    dwarf_regs::X5,
+    dwarf_regs::X6,
+    dwarf_regs::X7,
+    dwarf_regs::X8,
+    dwarf_regs::X9,
+    dwarf_regs::X10,
+    dwarf_regs::X11,
+    dwarf_regs::X12,
+    dwarf_regs::X13,
+    dwarf_regs::X14,
+    dwarf_regs::X15,
+    dwarf_regs::X16,
+    dwarf_regs::X17,
+    dwarf_regs::X18,
+    dwarf_regs::X19,
+    dwarf_regs::X20,
+    dwarf_regs::X21,
+    dwarf_regs::X22,
+    dwarf_regs::X23,
+    dwarf_regs::X24,
+    dwarf_regs::X25,
+    dwarf_regs::X26,
+    dwarf_regs::X27,
+    dwarf_regs::X28,
+    dwarf_regs::X29,
+    dwarf_regs::X30,
+    dwarf_regs::SP,
+    dwarf_regs::PC
+];
+
+#[repr(C)]
+#[derive(Clone, Default)]
+pub struct Regs {
+    x0: u64,
+    x1: u64,
+    x2: u64,
+    x3: u64,
+    x4: u64,
+    x5: u64,
+    x6: u64,
+    x7: u64,
+    x8: u64,
+    x9: u64,
+    x10: u64,
+    x11: u64,
+    x12: u64,
+    x13: u64,
+    x14: u64,
+    x15: u64,
+    x16: u64,
+    x17: u64,
+    x18: u64,
+    x19: u64,
+    x20: u64,
+    x21: u64,
+    x22: u64,
+    x23: u64,
+    x24: u64,
+    x25: u64,
+    x26: u64,
+    x27: u64,
+    x28: u64,
+    x29: u64,
+    x30: u64,
+    sp: u64,
+    pc: u64,
+    mask: u32
+}
+
+unsafe_impl_registers!( Regs, REGS, u64 );
+impl_local_regs!( Regs, "aarch64", get_regs_aarch64 );
+impl_regs_debug!( Regs, REGS, Arch );
+
+#[allow(dead_code)]
+pub struct Arch {}
+
+#[doc(hidden)]
+pub struct State {
+    unwind_cache: UnwindInfoCache
+}
+
+impl Architecture for Arch {
+    const NAME: &'static str = "aarch64";
+    const ENDIANNESS: Endianness = Endianness::LittleEndian;
+    const BITNESS: Bitness = Bitness::B64;
+    const STACK_POINTER_REG: u16 = dwarf_regs::SP;
+    const INSTRUCTION_POINTER_REG: u16 = dwarf_regs::PC;
+    const RETURN_ADDRESS_REG: u16 = dwarf_regs::X30;
+
+    type Endianity = LittleEndian;
+    type State = State;
+    type Regs = Regs;
+    type RegTy = u64;
+
+    fn register_name_str( register: u16 ) -> Option< &'static str > {
+        use self::dwarf_regs::*;
+
+        let name = match register {
+            X0 => "X0",
+            X1 => "X1",
+            X2 => "X2",
+            X3 => "X3",
+            X4 => "X4",
+            X5 => "X5",
+            X6 => "X6",
+            X7 => "X7",
+            X8 => "X8",
+            X9 => "X9",
+            X10 => "X10",
+            X11 => "X11",
+            X12 => "X12",
+            X13 => "X13",
+            X14 => "X14",
+            X15 => "X15",
+            X16 => "X16",
+            X17 => "X17",
+            X18 => "X18",
+            X19 => "X19",
+            X20 => "X20",
+            X21 => "X21",
+            X22 => "X22",
+            X23 => "X23",
+            X24 => "X24",
+            X25 => "X25",
+            X26 => "X26",
+            X27 => "X27",
+            X28 => "X28",
+            X29 => "FP",
+            X30 => "LR",
+            SP => "SP",
+            PC => "PC",
+            _ => return None
+        };
+
+        Some( name )
+    }
+
+    #[inline]
+    fn initial_state() -> Self::State {
+        State {
+            unwind_cache: UnwindInfoCache::new()
+        }
+    }
+
+    fn clear_cache( state: &mut Self::State ) {
+        state.unwind_cache.clear();
+    }
+
+    fn unwind< M: MemoryReader< Self > >(
+        nth_frame: usize,
+        memory: &M,
+        state: &mut Self::State,
+        regs: &mut Self::Regs,
+        initial_address: &mut Option< u64 >,
+        ra_address: &mut Option< u64 >
+    ) -> Option< UnwindStatus > {
+        let address = regs.get( dwarf_regs::PC ).unwrap();
+        if let Some( result ) = unwind_from_cache( memory, &mut state.unwind_cache, regs, address ) {
+            match result {
+                Ok( link_register_addr ) => {
+                    *ra_address = link_register_addr;
+                    return Some( UnwindStatus::InProgress );
+                },
+                Err( DwarfError::EndOfStack ) => {
+                    debug!( "Previous frame not found: EndOfStack" );
+                    return Some( UnwindStatus::Finished );
+                },
+                Err( error ) => {
+                    debug!( "Previous frame not found: {:?}", error );
+                    return None;
+                }
+            }
+        }
+
+        let binary = lookup_binary( nth_frame, memory, regs )?;
+        let binary_data = binary.data()?;
+
+        // Primarily unwind through the DWARF CFI present in `.eh_frame`/`.debug_frame`,
+        // reusing the same machinery used to unwind x86-64.
+        if binary_data.eh_frame_range().is_some() || binary_data.debug_frame_range().is_some() {
+            let mut initial_address_u64 = None;
+            let result = unwind(
+                memory,
+                &mut initial_address_u64,
+                &mut state.unwind_cache,
+                regs,
+                binary,
+                binary_data,
+                address,
+                nth_frame == 0
+            );
+
+            if let Some( initial_address_u64 ) = initial_address_u64 {
+                debug!( "Initial address for frame #{}: 0x{:016X}", nth_frame, initial_address_u64 );
+                *initial_address = Some( initial_address_u64 );
+            }
+
+            match result {
+                Ok( link_register_addr ) => {
+                    *ra_address = link_register_addr;
+                    return Some( UnwindStatus::InProgress );
+                },
+                Err( DwarfError::EndOfStack ) => {
+                    debug!( "Previous frame not found: EndOfStack" );
+                    return Some( UnwindStatus::Finished );
+                },
+                Err( error ) => {
+                    debug!( "DWARF CFI unwinding failed, falling back to frame pointers: {:?}", error );
+                }
+            }
+        } else {
+            debug!( "Binary '{}' has no CFI; falling back to frame pointers", binary_data.name() );
+        }
+
+        // Fall back to frame-pointer chaining: AArch64's procedure call standard keeps
+        // a linked list of {fp, lr} pairs pointed to by x29, so a missing or corrupt
+        // CFI table can still usually be recovered from.
+        let fp = regs.get( dwarf_regs::X29 ).unwrap();
+        if fp == 0 {
+            debug!( "Previous frame not found: FP is zero" );
+            return Some( UnwindStatus::Finished );
+        }
+
+        let saved_fp = memory.get_u64_at_address( nth_frame, fp )?;
+        let saved_lr = memory.get_u64_at_address( nth_frame, fp + 8 )?;
+
+        if is_terminal_frame_pointer( saved_fp, fp ) {
+            debug!( "Previous frame not found: FP is zero or not monotonically increasing" );
+            return Some( UnwindStatus::Finished );
+        }
+
+        regs.set( dwarf_regs::X29, saved_fp );
+        regs.set( dwarf_regs::SP, fp + 16 );
+        *ra_address = Some( fp + 8 );
+        regs.set( dwarf_regs::PC, saved_lr );
+
+        Some( UnwindStatus::InProgress )
+    }
+}
+
+// The outermost frame's saved FP is zero per AAPCS64, which is the normal
+// way the frame-pointer fallback terminates; a saved FP that isn't strictly
+// greater than the current one is a corrupt chain, which is terminated too
+// rather than looped on forever.
+fn is_terminal_frame_pointer( saved_fp: u64, fp: u64 ) -> bool {
+    saved_fp == 0 || saved_fp <= fp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_zero_saved_fp_is_terminal() {
+        assert!( is_terminal_frame_pointer( 0, 0x1000 ) );
+    }
+
+    #[test]
+    fn a_saved_fp_above_the_current_one_is_not_terminal() {
+        assert!( !is_terminal_frame_pointer( 0x2000, 0x1000 ) );
+    }
+
+    #[test]
+    fn a_saved_fp_equal_to_the_current_one_is_terminal() {
+        assert!( is_terminal_frame_pointer( 0x1000, 0x1000 ) );
+    }
+
+    #[test]
+    fn a_saved_fp_below_the_current_one_is_terminal() {
+        assert!( is_terminal_frame_pointer( 0x500, 0x1000 ) );
+    }
+}
@@ -0,0 +1,124 @@
+use std::fs::File;
+use std::io::{ self, Read };
+use std::path::Path;
+
+pub mod arm;
+pub mod aarch64;
+pub mod x86_64;
+
+// ELF `e_machine` values (as found in `Elf32_Ehdr`/`Elf64_Ehdr`) for every
+// target this crate can unwind.
+const EM_ARM: u16 = 40;
+const EM_X86_64: u16 = 62;
+const EM_AARCH64: u16 = 183;
+
+/// Picks the `Architecture` to unwind a binary with, based on its ELF
+/// `e_machine`. A single recording can reference binaries for more than one
+/// architecture (e.g. a 32-bit compat layer loaded into an otherwise 64-bit
+/// process), so this is resolved per-binary rather than once for the whole
+/// profiler run.
+pub fn architecture_name_for_elf_machine( e_machine: u16 ) -> Option< &'static str > {
+    match e_machine {
+        EM_ARM => Some( "arm" ),
+        EM_AARCH64 => Some( "aarch64" ),
+        EM_X86_64 => Some( "x86_64" ),
+        _ => None
+    }
+}
+
+/// Reads just enough of an ELF file at `path` to pick the `Architecture`
+/// it should be unwound with, without pulling in a full ELF parsing crate.
+/// This is what the per-binary architecture dispatch in the address space
+/// loader is expected to call for every binary it maps in.
+pub fn architecture_name_for_binary_path( path: &Path ) -> io::Result< Option< &'static str > > {
+    let mut header = [ 0u8; 20 ];
+    File::open( path )?.read_exact( &mut header )?;
+
+    if &header[ 0..4 ] != b"\x7fELF" {
+        return Ok( None );
+    }
+
+    // `e_machine` sits at the same offset (18) in both the 32-bit and the
+    // 64-bit header, right after `e_ident`/`e_type`; only its endianness
+    // (given by `e_ident[EI_DATA]`, byte 5) varies.
+    let e_machine_bytes = [ header[ 18 ], header[ 19 ] ];
+    let e_machine = match header[ 5 ] {
+        2 => u16::from_be_bytes( e_machine_bytes ),
+        _ => u16::from_le_bytes( e_machine_bytes )
+    };
+
+    Ok( architecture_name_for_elf_machine( e_machine ) )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn recognizes_every_supported_machine() {
+        assert_eq!( architecture_name_for_elf_machine( EM_ARM ), Some( "arm" ) );
+        assert_eq!( architecture_name_for_elf_machine( EM_AARCH64 ), Some( "aarch64" ) );
+        assert_eq!( architecture_name_for_elf_machine( EM_X86_64 ), Some( "x86_64" ) );
+    }
+
+    #[test]
+    fn rejects_an_unknown_machine() {
+        assert_eq!( architecture_name_for_elf_machine( 0xffff ), None );
+    }
+
+    #[test]
+    fn reads_e_machine_out_of_a_little_endian_elf_header() {
+        let mut header = vec![ 0u8; 64 ];
+        header[ 0..4 ].copy_from_slice( b"\x7fELF" );
+        header[ 4 ] = 2; // EI_CLASS: ELFCLASS64
+        header[ 5 ] = 1; // EI_DATA: little-endian
+        header[ 18..20 ].copy_from_slice( &EM_AARCH64.to_le_bytes() );
+
+        let mut file = tempfile();
+        file.write_all( &header ).unwrap();
+
+        assert_eq!( architecture_name_for_binary_path( file.path() ).unwrap(), Some( "aarch64" ) );
+    }
+
+    #[test]
+    fn returns_none_for_a_non_elf_file() {
+        let mut file = tempfile();
+        file.write_all( b"not an elf file at all" ).unwrap();
+
+        assert_eq!( architecture_name_for_binary_path( file.path() ).unwrap(), None );
+    }
+
+    struct TempFile {
+        path: std::path::PathBuf,
+        file: File
+    }
+
+    impl TempFile {
+        fn path( &self ) -> &Path {
+            &self.path
+        }
+    }
+
+    impl Write for TempFile {
+        fn write( &mut self, buf: &[ u8 ] ) -> io::Result< usize > {
+            self.file.write( buf )
+        }
+
+        fn flush( &mut self ) -> io::Result< () > {
+            self.file.flush()
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop( &mut self ) {
+            let _ = std::fs::remove_file( &self.path );
+        }
+    }
+
+    fn tempfile() -> TempFile {
+        let path = std::env::temp_dir().join( format!( "not-perf-arch-test-{:?}", std::thread::current().id() ) );
+        let file = File::create( &path ).expect( "failed to create temp file for test" );
+        TempFile { path, file }
+    }
+}
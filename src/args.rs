@@ -1,52 +1,49 @@
+use std::cell::RefCell;
 use std::ffi::OsString;
 
+use clap::{ Args as ClapArgs, Parser, Subcommand };
+
 use perf_event_open::EventSource;
 
 use crate::cmd_collate::CollateFormat;
 
-fn parse_event_source(source: &str) -> EventSource {
+fn parse_event_source(source: &str) -> Result<EventSource, String> {
     match source {
-        "hw_cpu_cycles" => EventSource::HwCpuCycles,
-        "hw_ref_cpu_cycles" => EventSource::HwRefCpuCycles,
-        "sw_cpu_clock" => EventSource::SwCpuClock,
-        "sw_page_faults" => EventSource::SwPageFaults,
-        "sw_dummy" => EventSource::SwDummy,
-        _ => unreachable!(),
+        "hw_cpu_cycles" => Ok(EventSource::HwCpuCycles),
+        "hw_ref_cpu_cycles" => Ok(EventSource::HwRefCpuCycles),
+        "sw_cpu_clock" => Ok(EventSource::SwCpuClock),
+        "sw_page_faults" => Ok(EventSource::SwPageFaults),
+        "sw_dummy" => Ok(EventSource::SwDummy),
+        _ => Err(format!("invalid event source '{}'", source)),
     }
 }
 
-fn parse_collate_format(format: &str) -> CollateFormat {
+fn parse_collate_format(format: &str) -> Result<CollateFormat, String> {
     match format {
-        "collapsed" => CollateFormat::Collapsed,
-        "perf-like" => CollateFormat::PerfLike,
-        _ => unreachable!(),
+        "collapsed" => Ok(CollateFormat::Collapsed),
+        "perf-like" => Ok(CollateFormat::PerfLike),
+        _ => Err(format!("invalid collate format '{}'", format)),
     }
 }
 
-fn try_parse_period(period: &str) -> Result<u64, <u64 as std::str::FromStr>::Err> {
-    let period = if period.ends_with("ms") {
-        period[0..period.len() - 2].parse::<u64>()? * 1000_000
-    } else if period.ends_with("us") {
-        period[0..period.len() - 2].parse::<u64>()? * 1000
-    } else if period.ends_with("ns") {
-        period[0..period.len() - 2].parse::<u64>()?
-    } else if period.ends_with("s") {
-        period[0..period.len() - 1].parse::<u64>()? * 1000_000_000
-    } else {
-        period.parse::<u64>()? * 1000_000_000
-    };
+fn try_parse_period(period: &str) -> Result<u64, String> {
+    let parse = || -> Result<u64, std::num::ParseIntError> {
+        let period = if period.ends_with("ms") {
+            period[0..period.len() - 2].parse::<u64>()? * 1000_000
+        } else if period.ends_with("us") {
+            period[0..period.len() - 2].parse::<u64>()? * 1000
+        } else if period.ends_with("ns") {
+            period[0..period.len() - 2].parse::<u64>()?
+        } else if period.ends_with("s") {
+            period[0..period.len() - 1].parse::<u64>()? * 1000_000_000
+        } else {
+            period.parse::<u64>()? * 1000_000_000
+        };
 
-    Ok(period)
-}
+        Ok(period)
+    };
 
-fn parse_period(period: &str) -> u64 {
-    match try_parse_period(period) {
-        Ok(period) => period,
-        Err(_) => {
-            eprintln!("error: invalid '--period' specified");
-            std::process::exit(1);
-        }
-    }
+    parse().map_err(|_| "invalid '--period' specified".to_owned())
 }
 
 pub enum TargetProcess {
@@ -55,16 +52,20 @@ pub enum TargetProcess {
     ByNameWaiting(String, u64),
 }
 
-#[derive(Clone, Debug)]
+#[derive(ClapArgs, Clone, Debug)]
 pub struct ProcessFilter {
     /// Profiles a process with a given PID (conflicts with --process)
+    #[arg(long, conflicts_with = "process")]
     pub pid: Option<u32>,
     /// Profiles a process with a given name (conflicts with --pid)
+    #[arg(long, conflicts_with = "pid")]
     pub process: Option<String>,
     /// Will wait for the profiled process to appear
+    #[arg(long)]
     pub wait: bool,
     /// Specifies the number of seconds which the profiler should wait
     /// for the process to appear; makes sense only when used with the `--wait` option
+    #[arg(long, default_value_t = 5)]
     pub wait_timeout: u32,
 }
 
@@ -97,151 +98,246 @@ impl Default for Granularity {
     }
 }
 
-fn parse_granularity(value: &str) -> Granularity {
+fn parse_granularity(value: &str) -> Result<Granularity, String> {
     match value {
-        "address" => Granularity::Address,
-        "function" => Granularity::Function,
-        "line" => Granularity::Line,
-        _ => unreachable!(),
+        "address" => Ok(Granularity::Address),
+        "function" => Ok(Granularity::Function),
+        "line" => Ok(Granularity::Line),
+        _ => Err(format!("invalid granularity '{}'", value)),
     }
 }
 
-#[derive(Debug)]
+#[derive(ClapArgs, Debug)]
 pub struct GenericProfilerArgs {
     /// The file to which the profiling data will be written
+    #[arg(short, long)]
     pub output: Option<OsString>,
 
     /// The number of samples to gather; unlimited by default
+    #[arg(long = "samples")]
     pub sample_count: Option<u64>,
 
     /// Determines for how many seconds the measurements will be gathered
+    #[arg(long)]
     pub time_limit: Option<u64>,
 
     /// Prevents anything in the profiler's address space from being swapped out; might increase memory usage significantly
+    #[arg(long)]
     pub lock_memory: bool,
 
     /// Disable online backtracing
+    #[arg(long)]
     pub offline: bool,
 
+    #[arg(long)]
     pub panic_on_partial_backtrace: bool,
 
+    #[command(flatten)]
     pub process_filter: ProcessFilter,
 }
 
-#[derive(Debug)]
+#[derive(ClapArgs, Debug)]
 pub struct RecordArgs {
     /// The frequency with which the measurements will be gathered
+    #[arg(short, long, default_value_t = 100)]
     pub frequency: u32,
 
     /// The source of perf events
+    #[arg(long = "event-source", value_parser = parse_event_source)]
     pub event_source: Option<EventSource>,
 
     /// Size of the gathered stack payloads (in bytes)
+    #[arg(long, default_value_t = 16384)]
     pub stack_size: u32,
 
     /// Gather data but do not do anything with it; useful only for testing
+    #[arg(long)]
     pub discard_all: bool,
 
+    /// Profiles off-CPU time instead of (or in addition to) on-CPU time by tracking
+    /// PERF_RECORD_SWITCH context-switch events and attributing the elapsed blocked
+    /// time to the stack captured at the moment the thread was switched out
+    #[arg(long)]
+    pub off_cpu: bool,
+
+    #[command(flatten)]
     pub profiler_args: GenericProfilerArgs,
 }
 
-#[derive(Debug)]
+#[derive(ClapArgs, Debug)]
 pub struct SharedCollationArgs {
     /// A file or directory with extra debugging symbols; can be specified multiple times
+    #[arg(long = "debug-symbols")]
     pub debug_symbols: Vec<OsString>,
 
     /// A path to a jitdump file
+    #[arg(long)]
     pub jitdump: Option<OsString>,
 
+    /// Fetches missing debug info by build-id from a debuginfod server; defaults to the
+    /// servers in `DEBUGINFOD_URLS` when no URL is given
+    #[arg(long, num_args = 0..=1, default_missing_value = "")]
+    pub debuginfod: Option<Option<String>>,
+
+    #[arg(long)]
     pub force_stack_size: Option<u32>,
 
+    #[arg(long)]
     pub omit: Vec<String>,
 
+    #[arg(long)]
     pub only_sample: Option<u64>,
 
     /// Completely ignores kernel callstacks
+    #[arg(long)]
     pub without_kernel_callstacks: bool,
 
     /// Only process the samples generated *after* this many seconds after launch.
+    #[arg(long)]
     pub from: Option<String>,
 
     /// Only process the samples generated *before* this many seconds after launch.
+    #[arg(long)]
     pub to: Option<String>,
 
     /// The input file to use; record it with the `record` subcommand
     pub input: OsString,
+
+    /// Lazily built on the first `--debuginfod` lookup and reused for every
+    /// one after that, so `Debuginfod`'s negative-result cache actually
+    /// persists across the whole collation run instead of being thrown away
+    /// (along with the client) after each individual lookup.
+    #[arg(skip)]
+    debuginfod_client: RefCell<Option<crate::debuginfod::Debuginfod>>,
 }
 
-#[derive(Debug)]
+impl SharedCollationArgs {
+    /// Resolves local debug info for a binary identified by `build_id`,
+    /// exactly like a `--debug-symbols` file: first consults `--debug-symbols`
+    /// (handled by the caller, since it already knows which of those paths
+    /// match which binary), then falls back to fetching it from a debuginfod
+    /// server when `--debuginfod` was passed and no local match was found.
+    /// Fetch failures are logged as warnings by `Debuginfod::fetch` itself and
+    /// simply result in `None` here, never an error.
+    pub fn resolve_debug_info_via_debuginfod(&self, build_id: &str) -> Option<OsString> {
+        // A bare `--debuginfod` arrives as an empty string (its
+        // `default_missing_value`), which means "use `DEBUGINFOD_URLS`".
+        let url = self.debuginfod.clone()?.filter(|url| !url.is_empty());
+        let mut client = self.debuginfod_client.borrow_mut();
+        let client = client.get_or_insert_with(|| crate::debuginfod::Debuginfod::new(url));
+        client.fetch(build_id).map(|path| path.into_os_string())
+    }
+}
+
+#[derive(ClapArgs, Clone, Debug)]
 pub struct ArgMergeThreads {
     /// Merge callstacks from all threads
+    #[arg(long)]
     pub merge_threads: bool,
 }
 
-#[derive(Debug)]
+#[derive(ClapArgs, Clone, Debug)]
 pub struct ArgGranularity {
     /// Specifies at what granularity the call frames will be merged
+    #[arg(long, value_parser = parse_granularity, default_value = "line")]
     pub granularity: Granularity,
 }
 
 #[cfg(feature = "inferno")]
-#[derive(Debug)]
+#[derive(ClapArgs, Debug)]
 pub struct FlamegraphArgs {
+    #[command(flatten)]
     pub collation_args: SharedCollationArgs,
 
+    #[command(flatten)]
     pub arg_merge_threads: ArgMergeThreads,
 
+    #[command(flatten)]
     pub arg_granularity: ArgGranularity,
 
     /// The file to which the flamegraph will be written to (instead of the stdout)
+    #[arg(short, long)]
     pub output: Option<OsString>,
 }
 
-#[derive(Debug)]
+#[derive(ClapArgs, Debug)]
 pub struct CsvArgs {
+    #[command(flatten)]
     pub collation_args: SharedCollationArgs,
 
     /// The sampling interval, in seconds
+    #[arg(long)]
     pub sampling_interval: Option<f64>,
 
+    /// Emits an extra column with the actual on-CPU nanoseconds attributed to each
+    /// bucket (clamped to the bucket's duration), instead of just a raw sample count
+    #[arg(long)]
+    pub cpu_delta: bool,
+
     /// The file to which the CSV will be written to (instead of the stdout)
+    #[arg(short, long)]
     pub output: Option<OsString>,
 }
 
-#[derive(Debug)]
+#[derive(ClapArgs, Debug)]
+pub struct FirefoxProfileArgs {
+    #[command(flatten)]
+    pub collation_args: SharedCollationArgs,
+
+    #[command(flatten)]
+    pub arg_merge_threads: ArgMergeThreads,
+
+    #[command(flatten)]
+    pub arg_granularity: ArgGranularity,
+
+    /// The file to which the Firefox Profiler JSON will be written to (instead of the stdout)
+    #[arg(short, long)]
+    pub output: Option<OsString>,
+}
+
+#[derive(ClapArgs, Debug)]
 pub struct TraceEventsArgs {
+    #[command(flatten)]
     pub collation_args: SharedCollationArgs,
 
+    #[command(flatten)]
     pub arg_granularity: ArgGranularity,
 
+    #[arg(long)]
     pub absolute_time: bool,
 
     /// The sampling period; samples within one sampling period will be merged together
+    #[arg(long, value_parser = try_parse_period)]
     pub period: Option<u64>,
 
     /// The file to which the trace events will be written to
+    #[arg(short, long)]
     pub output: OsString,
 }
 
-#[derive(Debug)]
+#[derive(ClapArgs, Debug)]
 pub struct CollateArgs {
+    #[command(flatten)]
     pub collation_args: SharedCollationArgs,
 
+    #[command(flatten)]
     pub arg_merge_threads: ArgMergeThreads,
 
+    #[command(flatten)]
     pub arg_granularity: ArgGranularity,
 
     /// Selects the output format
+    #[arg(long, value_parser = parse_collate_format, default_value = "collapsed")]
     pub format: CollateFormat,
 }
 
-#[derive(Debug)]
+#[derive(ClapArgs, Debug)]
 pub struct MetadataArgs {
     /// The input file to use; record it with the `record` subcommand
     pub input: OsString,
 }
 
-#[derive(Debug)]
+#[derive(Subcommand, Debug)]
 pub enum Opt {
     /// Records profiling information with perf_event_open
     Record(RecordArgs),
@@ -253,6 +349,9 @@ pub enum Opt {
     /// Emits a CSV file
     Csv(CsvArgs),
 
+    /// Emits a Firefox Profiler "processed profile" JSON file
+    FirefoxProfile(FirefoxProfileArgs),
+
     /// Emits trace events for use with Chromium's Trace Viewer
     TraceEvents(TraceEventsArgs),
 
@@ -262,3 +361,15 @@ pub enum Opt {
     /// Outputs rudimentary JSON-formatted metadata
     Metadata(MetadataArgs),
 }
+
+#[derive(Parser, Debug)]
+#[command(name = "not-perf")]
+struct Cli {
+    #[command(subcommand)]
+    command: Opt,
+}
+
+/// Parses `Opt` from the process's command-line arguments.
+pub fn parse() -> Opt {
+    Cli::parse().command
+}
@@ -0,0 +1,142 @@
+use std::fs;
+use std::io::Write;
+use std::error::Error;
+use std::collections::HashMap;
+
+use args::CsvArgs;
+use cmd_collate::{ Collation, collate };
+
+/// One bucket of `sampling_interval` seconds' worth of samples for a single
+/// thread, as emitted in the CSV.
+struct Bucket {
+    tid: u32,
+    bucket_index: u64,
+    sample_count: u64,
+    // On-CPU nanoseconds attributed to this bucket; only tracked when
+    // `--cpu-delta` is passed.
+    cpu_delta_ns: u64
+}
+
+// Tracks, per thread, the CPU-clock reading of the previous sample so the
+// delta between consecutive samples can be attributed to the bucket the
+// later sample falls into. A delta of zero means the thread's CPU clock did
+// not advance (e.g. blocked, or captured via off-CPU samples); a thread's
+// first sample also contributes zero, having no previous reading.
+struct CpuClockTracker {
+    last_cpu_time_ns: HashMap< u32, u64 >
+}
+
+impl CpuClockTracker {
+    fn new() -> Self {
+        CpuClockTracker { last_cpu_time_ns: HashMap::new() }
+    }
+
+    // Returns the on-CPU nanoseconds elapsed for `tid` since its last sample,
+    // clamped to `[0, interval_ns]` so a missed sample (or a stale counter
+    // after a thread was briefly descheduled and rescheduled) can't produce
+    // a delta larger than the bucket it's attributed to.
+    fn delta( &mut self, tid: u32, cpu_time_ns: u64, interval_ns: u64 ) -> u64 {
+        let delta = match self.last_cpu_time_ns.get( &tid ) {
+            Some( &previous ) => cpu_time_ns.saturating_sub( previous ),
+            None => 0
+        };
+
+        self.last_cpu_time_ns.insert( tid, cpu_time_ns );
+        delta.min( interval_ns )
+    }
+}
+
+fn collect_buckets( collation: &Collation, args: &CsvArgs, sampling_interval: f64 ) -> Vec< Bucket > {
+    let interval_ns = (sampling_interval * 1_000_000_000.0) as u64;
+    let mut buckets: HashMap< (u32, u64), Bucket > = HashMap::new();
+    let mut cpu_clock = CpuClockTracker::new();
+
+    for sample in collation.samples() {
+        let bucket_index = (sample.timestamp - collation.start_timestamp()) / interval_ns;
+        let cpu_delta_ns = if args.cpu_delta {
+            cpu_clock.delta( sample.tid, sample.cpu_time_ns, interval_ns )
+        } else {
+            0
+        };
+
+        let bucket = buckets.entry( (sample.tid, bucket_index) ).or_insert_with( || Bucket {
+            tid: sample.tid,
+            bucket_index,
+            sample_count: 0,
+            cpu_delta_ns: 0
+        });
+
+        bucket.sample_count += 1;
+        bucket.cpu_delta_ns = (bucket.cpu_delta_ns + cpu_delta_ns).min( interval_ns );
+    }
+
+    let mut buckets: Vec< _ > = buckets.into_values().collect();
+    buckets.sort_by_key( |bucket| (bucket.tid, bucket.bucket_index) );
+    buckets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_sample_for_a_thread_has_no_delta() {
+        let mut tracker = CpuClockTracker::new();
+        assert_eq!( tracker.delta( 1, 1_000, 1_000_000 ), 0 );
+    }
+
+    #[test]
+    fn delta_is_the_difference_from_the_previous_sample() {
+        let mut tracker = CpuClockTracker::new();
+        tracker.delta( 1, 1_000, 1_000_000 );
+        assert_eq!( tracker.delta( 1, 1_500, 1_000_000 ), 500 );
+    }
+
+    #[test]
+    fn delta_is_clamped_to_the_bucket_interval() {
+        let mut tracker = CpuClockTracker::new();
+        tracker.delta( 1, 0, 1_000 );
+        assert_eq!( tracker.delta( 1, 10_000, 1_000 ), 1_000 );
+    }
+
+    #[test]
+    fn a_cpu_clock_going_backwards_produces_a_zero_delta() {
+        let mut tracker = CpuClockTracker::new();
+        tracker.delta( 1, 10_000, 1_000_000 );
+        assert_eq!( tracker.delta( 1, 5_000, 1_000_000 ), 0 );
+    }
+
+    #[test]
+    fn threads_are_tracked_independently() {
+        let mut tracker = CpuClockTracker::new();
+        tracker.delta( 1, 1_000, 1_000_000 );
+        assert_eq!( tracker.delta( 2, 50, 1_000_000 ), 0 );
+    }
+}
+
+pub fn main( args: CsvArgs ) -> Result< (), Box< Error > > {
+    let collation = collate( &args.collation_args )?;
+    let sampling_interval = args.sampling_interval.unwrap_or( 1.0 );
+    let buckets = collect_buckets( &collation, &args, sampling_interval );
+
+    let mut output: Box< dyn Write > = match &args.output {
+        Some( path ) => Box::new( fs::File::create( path )? ),
+        None => Box::new( std::io::stdout() )
+    };
+
+    if args.cpu_delta {
+        writeln!( output, "tid,bucket,samples,cpu_delta_ns" )?;
+    } else {
+        writeln!( output, "tid,bucket,samples" )?;
+    }
+
+    for bucket in buckets {
+        if args.cpu_delta {
+            writeln!( output, "{},{},{},{}", bucket.tid, bucket.bucket_index, bucket.sample_count, bucket.cpu_delta_ns )?;
+        } else {
+            writeln!( output, "{},{},{}", bucket.tid, bucket.bucket_index, bucket.sample_count )?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,261 @@
+use std::fs;
+use std::ffi::OsStr;
+use std::error::Error;
+use std::io::Write;
+use std::collections::HashMap;
+
+use serde::Serialize;
+use serde_json;
+
+use args::{ SharedCollationArgs, ArgMergeThreads, ArgGranularity };
+use cmd_collate::{ Collation, StackFrame, collate };
+
+#[derive(Serialize)]
+struct Profile {
+    meta: Meta,
+    libs: Vec< Lib >,
+    #[serde(rename = "resourceTable")]
+    resource_table: ResourceTable,
+    #[serde(rename = "stringTable")]
+    string_table: Vec< String >,
+    #[serde(rename = "frameTable")]
+    frame_table: FrameTable,
+    #[serde(rename = "stackTable")]
+    stack_table: StackTable,
+    threads: Vec< Thread >
+}
+
+#[derive(Serialize)]
+struct Meta {
+    interval: f64,
+    #[serde(rename = "startTime")]
+    start_time: f64,
+    version: u32,
+    architecture: String
+}
+
+#[derive(Serialize)]
+struct Lib {
+    start: u64,
+    end: u64,
+    offset: u64,
+    name: String,
+    path: String,
+    #[serde(rename = "debugName")]
+    debug_name: String,
+    #[serde(rename = "breakpadId")]
+    breakpad_id: String
+}
+
+#[derive(Serialize)]
+struct Thread {
+    name: String,
+    tid: u32,
+    pid: u32,
+    samples: SampleTable
+}
+
+// The `resourceType` used for every resource we emit; the Firefox Profiler
+// assigns this to binaries symbolicated from a native library, as opposed
+// to e.g. a webhost or an addon.
+const RESOURCE_TYPE_LIBRARY: i64 = 1;
+
+// The Firefox Profiler's tables are column-oriented: a `schema` mapping
+// column name to index, plus one row per entry in `data`. These are shared
+// by every thread in the profile, so a stack that appears in more than one
+// thread is only ever interned once.
+#[derive(Serialize)]
+struct ResourceTable {
+    schema: HashMap< &'static str, u32 >,
+    // (name index, library index, resource type)
+    data: Vec< (i64, i64, i64) >
+}
+
+#[derive(Serialize)]
+struct FrameTable {
+    schema: HashMap< &'static str, u32 >,
+    // (name index, address, resource index)
+    data: Vec< (i64, u64, i64) >
+}
+
+#[derive(Serialize)]
+struct StackTable {
+    schema: HashMap< &'static str, u32 >,
+    // (parent stack index, frame index)
+    data: Vec< (i64, i64) >
+}
+
+#[derive(Serialize)]
+struct SampleTable {
+    schema: HashMap< &'static str, u32 >,
+    // (stack index, ms since profile start, weight)
+    data: Vec< (i64, f64, u64) >
+}
+
+pub struct Args< 'a > {
+    pub collation_args: SharedCollationArgs,
+    pub arg_merge_threads: ArgMergeThreads,
+    pub arg_granularity: ArgGranularity,
+    pub output: Option< &'a OsStr >
+}
+
+struct Tables {
+    string_table: Vec< String >,
+    resource_table: ResourceTable,
+    frame_table: FrameTable,
+    stack_table: StackTable,
+    stack_to_index: HashMap< Vec< StackFrame >, i64 >
+}
+
+// Interns every unique stack in the whole collation (across all threads)
+// into a single `frameTable` (one row per resolved frame) and a single
+// `stackTable` prefix tree, where row `i` is `{prefix: parent_stack_index,
+// frame: frame_index}`. Built once and shared by every thread's `samples`,
+// so a stack prefix common to several threads only ever gets one row.
+//
+// Every library referenced by a frame also gets a `resourceTable` row, built
+// from the same `libs` entries the archive's `BinaryInfo`/`BuildId` packets
+// populate, so the Firefox Profiler can resolve symbols for it; frames point
+// at a resource rather than a library directly, matching the processed
+// profile format.
+fn build_tables( collation: &Collation, libs: &[ Lib ] ) -> Tables {
+    let mut string_table = Vec::new();
+    let mut string_to_index = HashMap::new();
+    let mut resource_table = Vec::new();
+    let mut library_to_resource: HashMap< i64, i64 > = HashMap::new();
+    let mut frame_table = Vec::new();
+    let mut frame_to_index: HashMap< StackFrame, i64 > = HashMap::new();
+    let mut stack_table = Vec::new();
+    let mut stack_to_index: HashMap< Vec< StackFrame >, i64 > = HashMap::new();
+
+    for stack in collation.unique_stacks() {
+        let mut prefix: i64 = -1;
+        let mut path = Vec::new();
+        for frame in stack {
+            path.push( frame.clone() );
+            if let Some( &existing ) = stack_to_index.get( &path ) {
+                prefix = existing;
+                continue;
+            }
+
+            let frame_index = *frame_to_index.entry( frame.clone() ).or_insert_with( || {
+                let name_index = *string_to_index.entry( frame.name.clone() ).or_insert_with( || {
+                    string_table.push( frame.name.clone() );
+                    string_table.len() as i64 - 1
+                });
+
+                let resource_index = *library_to_resource.entry( frame.library_index ).or_insert_with( || {
+                    let name_index = libs.get( frame.library_index as usize )
+                        .map( |lib| *string_to_index.entry( lib.name.clone() ).or_insert_with( || {
+                            string_table.push( lib.name.clone() );
+                            string_table.len() as i64 - 1
+                        }))
+                        .unwrap_or( -1 );
+
+                    resource_table.push( (name_index, frame.library_index, RESOURCE_TYPE_LIBRARY) );
+                    resource_table.len() as i64 - 1
+                });
+
+                frame_table.push( (name_index, frame.address, resource_index) );
+                frame_table.len() as i64 - 1
+            });
+
+            stack_table.push( (prefix, frame_index) );
+            prefix = stack_table.len() as i64 - 1;
+            stack_to_index.insert( path.clone(), prefix );
+        }
+    }
+
+    let mut resource_schema = HashMap::new();
+    resource_schema.insert( "name", 0 );
+    resource_schema.insert( "lib", 1 );
+    resource_schema.insert( "type", 2 );
+
+    let mut frame_schema = HashMap::new();
+    frame_schema.insert( "name", 0 );
+    frame_schema.insert( "address", 1 );
+    frame_schema.insert( "resource", 2 );
+
+    let mut stack_schema = HashMap::new();
+    stack_schema.insert( "prefix", 0 );
+    stack_schema.insert( "frame", 1 );
+
+    Tables {
+        string_table,
+        resource_table: ResourceTable { schema: resource_schema, data: resource_table },
+        frame_table: FrameTable { schema: frame_schema, data: frame_table },
+        stack_table: StackTable { schema: stack_schema, data: stack_table },
+        stack_to_index
+    }
+}
+
+pub fn main( args: Args ) -> Result< (), Box< Error > > {
+    let collation = collate( &args.collation_args, args.arg_merge_threads.merge_threads, args.arg_granularity.granularity )?;
+
+    // Populated from the archive's `BinaryInfo`/`BuildId` packets; built
+    // before `build_tables` so it can look up each frame's library by index
+    // when interning the resource table.
+    let mut libs = Vec::new();
+    for binary in collation.binaries() {
+        libs.push( Lib {
+            start: binary.base_address,
+            end: binary.base_address + binary.memory_size,
+            offset: 0,
+            name: binary.name().to_owned(),
+            path: binary.path.clone(),
+            debug_name: binary.name().to_owned(),
+            breakpad_id: binary.build_id.clone().unwrap_or_default()
+        });
+    }
+
+    let tables = build_tables( &collation, &libs );
+
+    let mut sample_schema = HashMap::new();
+    sample_schema.insert( "stack", 0 );
+    sample_schema.insert( "time", 1 );
+    sample_schema.insert( "weight", 2 );
+
+    let mut threads = Vec::new();
+    for thread in collation.threads() {
+        let mut samples = Vec::new();
+        for sample in thread.samples() {
+            let stack_index = *tables.stack_to_index.get( &sample.stack ).unwrap_or( &-1 );
+            let time_ms = ( sample.timestamp - collation.start_timestamp() ) as f64 / 1_000_000.0;
+            samples.push( (stack_index, time_ms, sample.weight) );
+        }
+
+        threads.push( Thread {
+            name: thread.name.clone(),
+            tid: thread.tid,
+            pid: thread.pid,
+            samples: SampleTable { schema: sample_schema.clone(), data: samples }
+        });
+    }
+
+    let machine_info = collation.machine_info();
+    let profile = Profile {
+        meta: Meta {
+            interval: collation.sampling_interval_ms(),
+            start_time: machine_info.start_time_unix_ms(),
+            version: 24,
+            architecture: machine_info.architecture.clone()
+        },
+        libs,
+        resource_table: tables.resource_table,
+        string_table: tables.string_table,
+        frame_table: tables.frame_table,
+        stack_table: tables.stack_table,
+        threads
+    };
+
+    let json = serde_json::to_string( &profile )?;
+    match args.output {
+        Some( path ) => {
+            let mut fp = fs::File::create( path )?;
+            fp.write_all( json.as_bytes() )?;
+        },
+        None => println!( "{}", json )
+    }
+
+    Ok(())
+}
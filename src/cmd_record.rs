@@ -0,0 +1,81 @@
+use std::error::Error;
+use std::collections::HashMap;
+
+use archive::Packet;
+use args::RecordArgs;
+use off_cpu::{ OffCpuTracker, SampleKind };
+use perf_event_open::{ Perf, EventSource, EventStream };
+
+pub struct Args {
+    pub record_args: RecordArgs,
+}
+
+// Enables `PERF_RECORD_SWITCH` (and, on multi-core machines, the wide
+// `SWITCH_CPU_WIDE` variant so switches on other CPUs for the same thread
+// aren't missed) on every perf_event_open fd we open for this recording.
+// Only worth paying for when `--off-cpu` was actually requested.
+fn open_perf_fds( args: &RecordArgs ) -> Result< Vec< Perf >, Box< Error > > {
+    let source = args.event_source.unwrap_or( EventSource::SwCpuClock );
+    let mut perfs = Perf::build()
+        .frequency( args.frequency )
+        .sample_stack_size( args.stack_size )
+        .event_source( source );
+
+    if args.off_cpu {
+        perfs = perfs.enable_context_switches( true );
+    }
+
+    Ok( perfs.open_for_all_threads( &args.profiler_args.process_filter )? )
+}
+
+pub fn main( args: Args, mut on_packet: impl FnMut( Packet ) ) -> Result< (), Box< Error > > {
+    let record_args = args.record_args;
+    let mut off_cpu = OffCpuTracker::from_args( &record_args );
+    // `PERF_RECORD_SWITCH` doesn't carry a stack of its own, so we keep the
+    // most recently sampled stack per thread around to hand to the tracker
+    // the moment that thread switches out.
+    let mut last_stack: HashMap< u32, Vec< u64 > > = HashMap::new();
+    let perfs = open_perf_fds( &record_args )?;
+
+    // `open_for_all_threads` opens one fd per thread/CPU and all of them fill
+    // concurrently in real time, so they have to be multiplexed rather than
+    // drained one at a time (draining fd #1 to exhaustion first would starve
+    // every other fd's ring buffer for the whole recording). `EventStream`
+    // polls every fd each round and yields events merged in a single global
+    // timestamp order, which both keeps per-fd buffers from overflowing and
+    // keeps same-tid samples in order for downstream consumers like the
+    // CSV cpu-delta column.
+    for event in EventStream::new( &perfs ) {
+        match event {
+            perf_event_open::Event::Sample { tid, stack, .. } => {
+                if off_cpu.is_some() {
+                    last_stack.insert( tid, stack.clone() );
+                }
+                on_packet( Packet::sample( tid, stack, SampleKind::OnCpu ) );
+            },
+            perf_event_open::Event::ContextSwitchOut { tid, timestamp } => {
+                if let Some( tracker ) = off_cpu.as_mut() {
+                    let stack = last_stack.get( &tid ).cloned().unwrap_or_default();
+                    tracker.handle_switch_out( tid, timestamp, stack );
+                }
+            },
+            perf_event_open::Event::ContextSwitchIn { tid, timestamp } => {
+                if let Some( tracker ) = off_cpu.as_mut() {
+                    if let Some( off_cpu_sample ) = tracker.handle_switch_in( tid, timestamp ) {
+                        on_packet( Packet::sample( off_cpu_sample.tid, off_cpu_sample.stack, off_cpu_sample.kind ) );
+                    }
+                }
+            }
+        }
+    }
+
+    // Whatever's still coalescing when the recording stops would otherwise
+    // be lost, since it's only emitted by a later switch-in that never comes.
+    if let Some( tracker ) = off_cpu.as_mut() {
+        for off_cpu_sample in tracker.flush() {
+            on_packet( Packet::sample( off_cpu_sample.tid, off_cpu_sample.stack, off_cpu_sample.kind ) );
+        }
+    }
+
+    Ok(())
+}
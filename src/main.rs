@@ -0,0 +1,52 @@
+#[macro_use]
+extern crate log;
+
+mod args;
+mod cmd_metadata;
+mod cmd_firefox_profile;
+mod cmd_record;
+mod cmd_csv;
+mod debuginfod;
+mod off_cpu;
+
+// `cmd_trace_events`, `cmd_collate` and (behind the `inferno` feature)
+// `cmd_flamegraph` are unchanged by this series; their dispatch below is
+// unaffected by anything in these commits.
+mod cmd_trace_events;
+mod cmd_collate;
+#[cfg(feature = "inferno")]
+mod cmd_flamegraph;
+
+use std::error::Error;
+use std::process;
+
+use args::Opt;
+
+fn dispatch( opt: Opt ) -> Result< (), Box< Error > > {
+    match opt {
+        Opt::Record( record_args ) => {
+            cmd_record::main( cmd_record::Args { record_args }, |packet| archive::write_packet( packet ) )
+        },
+        #[cfg(feature = "inferno")]
+        Opt::Flamegraph( args ) => cmd_flamegraph::main( args ),
+        Opt::Csv( args ) => cmd_csv::main( args ),
+        Opt::FirefoxProfile( args ) => cmd_firefox_profile::main( cmd_firefox_profile::Args {
+            collation_args: args.collation_args,
+            arg_merge_threads: args.arg_merge_threads,
+            arg_granularity: args.arg_granularity,
+            output: args.output.as_deref()
+        }),
+        Opt::TraceEvents( args ) => cmd_trace_events::main( args ),
+        Opt::Collate( args ) => cmd_collate::main( args ),
+        Opt::Metadata( args ) => cmd_metadata::main( cmd_metadata::Args { input_path: &args.input } )
+    }
+}
+
+fn main() {
+    // Parses the subcommand and its flags with the clap definitions in `args`.
+    let opt = args::parse();
+    if let Err( error ) = dispatch( opt ) {
+        eprintln!( "error: {}", error );
+        process::exit( 1 );
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+
+use args::RecordArgs;
+
+/// Distinguishes a sample taken while a thread was actually running from a
+/// synthetic one reconstructed from `PERF_RECORD_SWITCH` events; threaded
+/// through archive packets so collation/flamegraph/trace-events can keep
+/// on-CPU and off-CPU samples in separate flame graphs.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SampleKind {
+    OnCpu,
+    OffCpu,
+}
+
+/// The stack captured the moment a thread was switched out, kept until it's
+/// switched back in so the blocked interval can be attributed to it.
+struct PendingSwitch {
+    switched_out_at: u64,
+    stack: Vec<u64>,
+}
+
+/// An off-CPU sample that has already been switched back in, but is kept
+/// around a little longer in case the thread immediately blocks again —
+/// in which case the new blocked interval is folded into this one instead
+/// of being emitted as a separate sample.
+struct OpenSample {
+    switched_in_at: u64,
+    stack: Vec<u64>,
+    weight: u64,
+}
+
+/// A synthetic off-CPU sample covering one or more coalesced switch-out /
+/// switch-in intervals for a thread, weighted by the total elapsed blocked
+/// nanoseconds and attributed to the stack captured at the first switch-out.
+pub struct OffCpuSample {
+    pub tid: u32,
+    pub stack: Vec<u64>,
+    pub weight: u64,
+    pub kind: SampleKind,
+}
+
+/// Reconstructs off-CPU time from `PERF_RECORD_SWITCH` / `SWITCH_CPU_WIDE`
+/// events. A thread that blocks, briefly runs, then blocks again (e.g. mutex
+/// contention) would otherwise produce one synthetic sample per cycle; since
+/// adjacent cycles within `coalesce_window` nanoseconds of each other are
+/// folded into a single emitted sample, very short slices don't explode the
+/// sample count.
+pub struct OffCpuTracker {
+    pending: HashMap<u32, PendingSwitch>,
+    open: HashMap<u32, OpenSample>,
+    coalesce_window: u64,
+}
+
+impl OffCpuTracker {
+    pub fn new() -> Self {
+        OffCpuTracker {
+            pending: HashMap::new(),
+            open: HashMap::new(),
+            // Switch-in/switch-out cycles for the same thread within this many
+            // nanoseconds of each other are folded into the same open sample.
+            coalesce_window: 50_000,
+        }
+    }
+
+    /// Builds a tracker iff `--off-cpu` was passed on the `record` subcommand;
+    /// returns `None` so the common on-CPU-only path skips context-switch
+    /// bookkeeping entirely.
+    pub fn from_args(args: &RecordArgs) -> Option<Self> {
+        if args.off_cpu {
+            Some(OffCpuTracker::new())
+        } else {
+            None
+        }
+    }
+
+    /// Call when a `PERF_RECORD_SWITCH` (switch-out) event arrives for `tid`,
+    /// together with the most recently captured user+kernel stack for that thread.
+    pub fn handle_switch_out(&mut self, tid: u32, timestamp: u64, stack: Vec<u64>) {
+        match self.pending.get_mut(&tid) {
+            Some(pending) if timestamp.saturating_sub(pending.switched_out_at) < self.coalesce_window => {
+                pending.stack = stack;
+            }
+            _ => {
+                self.pending.insert(tid, PendingSwitch { switched_out_at: timestamp, stack });
+            }
+        }
+    }
+
+    /// Call when a `PERF_RECORD_SWITCH` (switch-in) event arrives for `tid`.
+    /// The blocked interval ending here is kept open rather than returned: if
+    /// the thread re-blocks within `coalesce_window` nanoseconds of this
+    /// switch-in, the next `handle_switch_out`/`handle_switch_in` pair extends
+    /// it instead of starting a new one. An open sample is only handed back
+    /// (by a later call) once the thread has stayed on-CPU for at least
+    /// `coalesce_window` before blocking again — or via `flush`, if it never
+    /// blocks again before recording ends.
+    pub fn handle_switch_in(&mut self, tid: u32, timestamp: u64) -> Option<OffCpuSample> {
+        let pending = self.pending.remove(&tid)?;
+        let weight = timestamp.saturating_sub(pending.switched_out_at);
+
+        // The gap that matters for coalescing is how long the thread actually
+        // ran *between* the two blocked intervals (this switch-out minus the
+        // previous switch-in), not the total time since the previous sample
+        // was opened — otherwise a thread that's merely blocked for a long
+        // time would itself count against the window.
+        match self.open.get_mut(&tid) {
+            Some(open) if pending.switched_out_at.saturating_sub(open.switched_in_at) < self.coalesce_window => {
+                open.weight += weight;
+                open.switched_in_at = timestamp;
+                None
+            }
+            _ => {
+                self.open.insert(tid, OpenSample { switched_in_at: timestamp, stack: pending.stack, weight })
+                    .and_then(|open| Self::into_sample(tid, open))
+            }
+        }
+    }
+
+    /// Emits every thread's still-open sample; call once recording has ended
+    /// so the last coalesced interval of each thread isn't silently dropped.
+    pub fn flush(&mut self) -> Vec<OffCpuSample> {
+        self.open.drain().filter_map(|(tid, open)| Self::into_sample(tid, open)).collect()
+    }
+
+    fn into_sample(tid: u32, open: OpenSample) -> Option<OffCpuSample> {
+        if open.weight == 0 {
+            return None;
+        }
+
+        Some(OffCpuSample { tid, stack: open.stack, weight: open.weight, kind: SampleKind::OffCpu })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coalesces_short_on_cpu_gaps_into_one_sample() {
+        let mut tracker = OffCpuTracker::new();
+
+        tracker.handle_switch_out(1, 0, vec![0xAAAA]);
+        assert!(tracker.handle_switch_in(1, 1_000).is_none());
+
+        // Only ran for 100ns before blocking again, well under the 50_000ns
+        // coalesce window, so this should fold into the still-open sample.
+        tracker.handle_switch_out(1, 1_100, vec![0xBBBB]);
+        assert!(tracker.handle_switch_in(1, 3_100).is_none());
+
+        let samples = tracker.flush();
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].tid, 1);
+        assert_eq!(samples[0].weight, 1_000 + 2_000);
+    }
+
+    #[test]
+    fn does_not_coalesce_across_a_long_on_cpu_run() {
+        let mut tracker = OffCpuTracker::new();
+
+        tracker.handle_switch_out(1, 0, vec![0xAAAA]);
+        assert!(tracker.handle_switch_in(1, 1_000).is_none());
+
+        // Ran for well over the coalesce window before blocking again, so the
+        // first sample should be flushed out here instead of merged into it.
+        tracker.handle_switch_out(1, 101_000, vec![0xBBBB]);
+        let emitted = tracker.handle_switch_in(1, 101_500)
+            .expect("a long on-cpu gap should flush the previous sample");
+        assert_eq!(emitted.weight, 1_000);
+
+        let remaining = tracker.flush();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].weight, 500);
+    }
+
+    #[test]
+    fn zero_weight_samples_are_dropped() {
+        let mut tracker = OffCpuTracker::new();
+        tracker.handle_switch_out(1, 0, vec![]);
+        assert!(tracker.handle_switch_in(1, 0).is_none());
+        assert!(tracker.flush().is_empty());
+    }
+}